@@ -2,15 +2,38 @@ use crate::{error::RbacError, rbac::RoleManager, Result};
 
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     sync::{Arc, RwLock},
 };
 
+/// An edge in the role graph, carrying the arbitrary params an
+/// `add_link_with_condition` call was given. A link added through the plain
+/// `add_link` has empty `params` and is always followed; conditional links
+/// are only followed while `link_condition_fn` returns true for their
+/// params, letting expired or inactive grants be ignored without deleting
+/// the underlying edge.
+#[derive(Clone)]
+struct Edge {
+    role: Arc<RwLock<Role>>,
+    params: Vec<String>,
+}
+
+fn edge_active(params: &[String], condition_fn: Option<fn(&[String]) -> bool>) -> bool {
+    params.is_empty() || condition_fn.is_none_or(|f| f(params))
+}
+
 #[derive(Clone)]
 pub struct DefaultRoleManager {
     all_roles: HashMap<String, Arc<RwLock<Role>>>,
     max_hierarchy_level: usize,
     matching_fn: Option<fn(&str, &str) -> bool>,
+    domain_matching_fn: Option<fn(&str, &str) -> bool>,
+    link_condition_fn: Option<fn(&[String]) -> bool>,
+    // Bumped on every add_link/delete_link/clear. Each `Role` tags its
+    // memoized ancestor set with the generation it was computed at, so a
+    // stale cache is detected by a generation mismatch instead of having to
+    // eagerly walk the whole graph to invalidate it.
+    generation: u64,
 }
 
 impl DefaultRoleManager {
@@ -19,25 +42,226 @@ impl DefaultRoleManager {
             all_roles: HashMap::new(),
             max_hierarchy_level,
             matching_fn: None,
+            domain_matching_fn: None,
+            link_condition_fn: None,
+            generation: 0,
         }
     }
 
-    fn create_role(&mut self, name: &str) -> Arc<RwLock<Role>> {
-        let role = Arc::clone(
-            self.all_roles
-                .entry(name.to_owned())
-                .or_insert_with(|| Arc::new(RwLock::new(Role::new(name)))),
+    /// Registers a pattern-matching function over *domains* only, kept
+    /// separate from `matching_fn` (which patterns over role names). It is
+    /// called as `domain_matching_fn(queried_domain, known_domain)`, the same
+    /// argument order `has_role` uses for `matching_fn`. With a function that
+    /// treats its second argument as the pattern, a query like
+    /// `has_link("alice", "admin", Some("tenant_42"))` succeeds against a
+    /// link declared for domain pattern `"tenant_*"`; one that treats its
+    /// first argument as the pattern lets a query for `"tenant_*"` reach the
+    /// links of every known domain it matches.
+    pub fn add_domain_matching_fn(&mut self, domain_matching_fn: fn(&str, &str) -> bool) {
+        self.domain_matching_fn = Some(domain_matching_fn);
+    }
+
+    /// Registers the predicate used to evaluate conditional links added via
+    /// `add_link_with_condition`. Changing it can flip which edges a
+    /// traversal follows, so it invalidates every cached ancestor set.
+    pub fn set_link_condition_fn(&mut self, link_condition_fn: fn(&[String]) -> bool) {
+        self.link_condition_fn = Some(link_condition_fn);
+        self.generation += 1;
+    }
+
+    /// Like `add_link`, but the edge is only followed by `has_link` (and the
+    /// implicit-role/user queries built on it) while `link_condition_fn`
+    /// returns true for `params` — e.g. a time-bounded or attribute-gated
+    /// grant. With no `link_condition_fn` registered the edge behaves like a
+    /// plain link, since `edge_active` treats a missing `link_condition_fn`
+    /// as true; an empty `params` also makes it a plain, always-followed
+    /// link.
+    pub fn add_link_with_condition(
+        &mut self,
+        name1: &str,
+        name2: &str,
+        domain: Option<&str>,
+        params: Vec<String>,
+    ) {
+        let (name1, name2) = Self::scoped_names(name1, name2, domain);
+
+        let role1 = self.create_role(&name1);
+        let role2 = self.create_role(&name2);
+
+        role1
+            .write()
+            .unwrap()
+            .add_role_with_params(Arc::clone(&role2), params);
+        self.generation += 1;
+    }
+
+    /// Every role reachable from `name` (directly or transitively) within
+    /// `max_hierarchy_level` hops, i.e. the full implicit membership that
+    /// `has_link` already searches through but `get_roles` does not surface.
+    pub fn get_implicit_roles(&mut self, name: &str, domain: Option<&str>) -> Vec<String> {
+        match domain {
+            Some(domain) => {
+                let mut roles: Vec<String> = self
+                    .resolve_domains(domain)
+                    .into_iter()
+                    .flat_map(|d| self.get_implicit_roles_scoped(name, Some(&d)))
+                    .collect();
+                roles.sort_unstable();
+                roles.dedup();
+                roles
+            }
+            None => self.get_implicit_roles_scoped(name, None),
+        }
+    }
+
+    fn get_implicit_roles_scoped(&mut self, name: &str, domain: Option<&str>) -> Vec<String> {
+        let name: Cow<str> = if let Some(domain) = domain {
+            format!("{}::{}", domain, name).into()
+        } else {
+            name.into()
+        };
+
+        if !self.has_role(&name) {
+            return vec![];
+        }
+
+        let role = self.create_role(&name);
+        let roles = role.write().unwrap().implicit_roles(
+            self.max_hierarchy_level,
+            self.generation,
+            self.link_condition_fn,
         );
 
+        if let Some(domain) = domain {
+            roles
+                .into_iter()
+                .map(|mut x| {
+                    x.replace_range(0..domain.len() + 2, "");
+                    x
+                })
+                .collect()
+        } else {
+            roles
+        }
+    }
+
+    /// Every subject that reaches `name` (directly or transitively) within
+    /// `max_hierarchy_level` hops, i.e. the reverse of `get_implicit_roles`.
+    ///
+    /// Like `get_users`, this does not distinguish end users from
+    /// intermediate roles: if `g1` is itself a member of `name`, `g1` is
+    /// included in the result alongside any plain users that reach `name`
+    /// only via `g1`.
+    pub fn get_implicit_users(&self, name: &str, domain: Option<&str>) -> Vec<String> {
+        match domain {
+            Some(domain) => {
+                let mut users: Vec<String> = self
+                    .resolve_domains(domain)
+                    .into_iter()
+                    .flat_map(|d| self.get_implicit_users_scoped(name, Some(&d)))
+                    .collect();
+                users.sort_unstable();
+                users.dedup();
+                users
+            }
+            None => self.get_implicit_users_scoped(name, None),
+        }
+    }
+
+    fn get_implicit_users_scoped(&self, name: &str, domain: Option<&str>) -> Vec<String> {
+        let name: Cow<str> = if let Some(domain) = domain {
+            format!("{}::{}", domain, name).into()
+        } else {
+            name.into()
+        };
+
+        if !self.has_role(&name) {
+            return vec![];
+        }
+
+        // Reverse adjacency (child name -> parent names), built once per
+        // call since roles are stored as forward edges only. Inactive
+        // conditional edges are skipped, same as the forward traversal.
+        let mut reverse: HashMap<String, Vec<String>> = HashMap::new();
+        for (key, role) in self.all_roles.iter() {
+            for edge in role.read().unwrap().roles.iter() {
+                if !edge_active(&edge.params, self.link_condition_fn) {
+                    continue;
+                }
+                let child_name = edge.role.read().unwrap().name.clone();
+                reverse.entry(child_name).or_default().push(key.clone());
+            }
+        }
+
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut result = vec![];
+        let mut queue: VecDeque<(String, usize)> = VecDeque::new();
+        if let Some(parents) = reverse.get(name.as_ref()) {
+            queue.extend(
+                parents
+                    .iter()
+                    .cloned()
+                    .map(|p| (p, self.max_hierarchy_level)),
+            );
+        }
+
+        while let Some((cur, remaining)) = queue.pop_front() {
+            if remaining == 0 || !visited.insert(cur.clone()) {
+                continue;
+            }
+            result.push(cur.clone());
+            if let Some(parents) = reverse.get(&cur) {
+                queue.extend(parents.iter().cloned().map(|p| (p, remaining - 1)));
+            }
+        }
+
+        if let Some(domain) = domain {
+            result
+                .into_iter()
+                .map(|mut x| {
+                    x.replace_range(0..domain.len() + 2, "");
+                    x
+                })
+                .collect()
+        } else {
+            result
+        }
+    }
+
+    /// Interns `name`, returning its existing `Role` unchanged if already
+    /// known. The `matching_fn` index is only ever built once per name, at
+    /// the moment it's first interned, instead of being rescanned on every
+    /// call — `add_link`/`has_link`/`get_roles` all route through here, so
+    /// repeatedly resolving an existing name used to make bulk policy
+    /// loading effectively O(n^2).
+    fn create_role(&mut self, name: &str) -> Arc<RwLock<Role>> {
+        if let Some(role) = self.all_roles.get(name) {
+            return Arc::clone(role);
+        }
+
+        let role = Arc::new(RwLock::new(Role::new(name)));
+
         if let Some(matching_fn) = self.matching_fn {
-            let mut role_locked = role.write().unwrap();
-            for (n, r) in self.all_roles.iter().filter(|(n, _)| *n != name) {
+            for (n, r) in self.all_roles.iter() {
+                // The new role may match an existing one (it inherits `r`),
+                // and an existing one may match the newcomer (it inherits
+                // the new role) — both directions have to be indexed now,
+                // since this is the only time `name` is ever compared
+                // against the roles that already exist.
                 if matching_fn(name, n) {
-                    role_locked.add_role(Arc::clone(r));
+                    role.write().unwrap().add_role(Arc::clone(r));
+                }
+                if matching_fn(n, name) {
+                    r.write().unwrap().add_role(Arc::clone(&role));
                 }
             }
+            // Either side above may have added an edge reaching into an
+            // already-cached ancestor set; bump the generation so those
+            // caches recompute on next use instead of answering stale.
+            self.generation += 1;
         }
 
+        self.all_roles.insert(name.to_owned(), Arc::clone(&role));
         role
     }
 
@@ -48,75 +272,115 @@ impl DefaultRoleManager {
             self.all_roles.contains_key(name)
         }
     }
-}
 
-impl RoleManager for DefaultRoleManager {
-    fn add_matching_fn(&mut self, matching_fn: fn(&str, &str) -> bool) {
-        self.matching_fn = Some(matching_fn);
+    /// Distinct domains currently known to the role graph, derived from the
+    /// `"domain::name"` keys in `all_roles`. Keys with no `"::"` separator
+    /// (the domain-less case) are excluded.
+    fn domains(&self) -> Vec<String> {
+        let mut domains: Vec<String> = self
+            .all_roles
+            .values()
+            .filter_map(|role| role.read().unwrap().domain().map(|d| d.to_owned()))
+            .collect();
+        domains.sort_unstable();
+        domains.dedup();
+        domains
     }
 
-    fn add_link(&mut self, name1: &str, name2: &str, domain: Option<&str>) {
-        let (name1, name2): (Cow<str>, Cow<str>) = if let Some(domain) = domain {
-            (
-                format!("{}::{}", domain, name1).into(),
-                format!("{}::{}", domain, name2).into(),
-            )
-        } else {
-            (name1.into(), name2.into())
-        };
-
-        let role1 = self.create_role(&name1);
-        let role2 = self.create_role(&name2);
-
-        role1.write().unwrap().add_role(Arc::clone(&role2));
+    /// Every domain currently known to the role graph. Domain-less links
+    /// (names with no `"::"` prefix) don't contribute a domain.
+    pub fn get_all_domains(&self) -> Vec<String> {
+        self.domains()
     }
 
-    fn delete_link(&mut self, name1: &str, name2: &str, domain: Option<&str>) -> Result<()> {
-        let (name1, name2): (Cow<str>, Cow<str>) = if let Some(domain) = domain {
-            (
-                format!("{}::{}", domain, name1).into(),
-                format!("{}::{}", domain, name2).into(),
-            )
-        } else {
-            (name1.into(), name2.into())
-        };
-
-        if !self.has_role(&name1) || !self.has_role(&name2) {
-            return Err(RbacError::NotFound(format!("{} OR {}", name1, name2)).into());
-        }
+    /// Every domain in which `name` participates as a subject, i.e. appears
+    /// on the left or right of some `add_link(.., domain)` call. When
+    /// `matching_fn` is set, a role key's subject is also considered a match
+    /// if it pattern-matches `name`, mirroring how `has_role` resolves
+    /// un-interned queries.
+    pub fn get_domains_for_user(&self, name: &str) -> Vec<String> {
+        let mut domains: Vec<String> = self
+            .all_roles
+            .values()
+            .filter_map(|role| {
+                let role = role.read().unwrap();
+                let domain = role.domain()?;
+                let subject = &role.name[domain.len() + 2..];
 
-        let role1 = self.create_role(&name1);
-        let role2 = self.create_role(&name2);
+                let matches = match self.matching_fn {
+                    Some(matching_fn) => subject == name || matching_fn(name, subject),
+                    None => subject == name,
+                };
 
-        role1.write().unwrap().delete_role(role2);
-        Ok(())
+                if matches {
+                    Some(domain.to_owned())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        domains.sort_unstable();
+        domains.dedup();
+        domains
     }
 
-    fn has_link(&mut self, name1: &str, name2: &str, domain: Option<&str>) -> bool {
-        if name1 == name2 {
-            return true;
+    /// Resolves a domain query to the concrete domain(s) it should act on.
+    /// When `domain_matching_fn` is set, the result is every known domain
+    /// that equals `domain` or for which `domain_matching_fn(domain, known)`
+    /// holds; if no known domain qualifies, it is just `[domain]`, so a plain
+    /// domain continues to work even before any link under that exact name
+    /// has been added. Without a domain-matching fn this is just `[domain]`.
+    fn resolve_domains(&self, domain: &str) -> Vec<String> {
+        match self.domain_matching_fn {
+            Some(domain_matching_fn) => {
+                let mut domains: Vec<String> = self
+                    .domains()
+                    .into_iter()
+                    .filter(|d| d == domain || domain_matching_fn(domain, d))
+                    .collect();
+                if domains.is_empty() {
+                    domains.push(domain.to_owned());
+                }
+                domains
+            }
+            None => vec![domain.to_owned()],
         }
+    }
 
-        let (name1, name2): (Cow<str>, Cow<str>) = if let Some(domain) = domain {
+    fn scoped_names<'a>(
+        name1: &'a str,
+        name2: &'a str,
+        domain: Option<&str>,
+    ) -> (Cow<'a, str>, Cow<'a, str>) {
+        if let Some(domain) = domain {
             (
                 format!("{}::{}", domain, name1).into(),
                 format!("{}::{}", domain, name2).into(),
             )
         } else {
             (name1.into(), name2.into())
-        };
+        }
+    }
+
+    /// `has_link` against a single, already-resolved domain (no pattern
+    /// expansion). This is the pre-existing per-key lookup logic.
+    fn has_link_scoped(&mut self, name1: &str, name2: &str, domain: Option<&str>) -> bool {
+        let (name1, name2) = Self::scoped_names(name1, name2, domain);
 
         if !self.has_role(&name1) || !self.has_role(&name2) {
             return false;
         }
 
-        self.create_role(&name1)
-            .write()
-            .unwrap()
-            .has_role(&name2, self.max_hierarchy_level)
+        self.create_role(&name1).write().unwrap().has_role(
+            &name2,
+            self.max_hierarchy_level,
+            self.generation,
+            self.link_condition_fn,
+        )
     }
 
-    fn get_roles(&mut self, name: &str, domain: Option<&str>) -> Vec<String> {
+    /// `get_roles` against a single, already-resolved domain.
+    fn get_roles_scoped(&mut self, name: &str, domain: Option<&str>) -> Vec<String> {
         let name: Cow<str> = if let Some(domain) = domain {
             format!("{}::{}", domain, name).into()
         } else {
@@ -144,7 +408,8 @@ impl RoleManager for DefaultRoleManager {
         }
     }
 
-    fn get_users(&self, name: &str, domain: Option<&str>) -> Vec<String> {
+    /// `get_users` against a single, already-resolved domain.
+    fn get_users_scoped(&self, name: &str, domain: Option<&str>) -> Vec<String> {
         let name: Cow<str> = if let Some(domain) = domain {
             format!("{}::{}", domain, name).into()
         } else {
@@ -174,16 +439,98 @@ impl RoleManager for DefaultRoleManager {
             })
             .collect()
     }
+}
+
+impl RoleManager for DefaultRoleManager {
+    fn add_matching_fn(&mut self, matching_fn: fn(&str, &str) -> bool) {
+        self.matching_fn = Some(matching_fn);
+    }
+
+    fn add_link(&mut self, name1: &str, name2: &str, domain: Option<&str>) {
+        let (name1, name2) = Self::scoped_names(name1, name2, domain);
+
+        let role1 = self.create_role(&name1);
+        let role2 = self.create_role(&name2);
+
+        role1.write().unwrap().add_role(Arc::clone(&role2));
+        self.generation += 1;
+    }
+
+    fn delete_link(&mut self, name1: &str, name2: &str, domain: Option<&str>) -> Result<()> {
+        let (name1, name2) = Self::scoped_names(name1, name2, domain);
+
+        if !self.has_role(&name1) || !self.has_role(&name2) {
+            return Err(RbacError::NotFound(format!("{} OR {}", name1, name2)).into());
+        }
+
+        let role1 = self.create_role(&name1);
+        let role2 = self.create_role(&name2);
+
+        role1.write().unwrap().delete_role(role2);
+        self.generation += 1;
+        Ok(())
+    }
+
+    fn has_link(&mut self, name1: &str, name2: &str, domain: Option<&str>) -> bool {
+        if name1 == name2 {
+            return true;
+        }
+
+        match domain {
+            Some(domain) => self
+                .resolve_domains(domain)
+                .into_iter()
+                .any(|d| self.has_link_scoped(name1, name2, Some(&d))),
+            None => self.has_link_scoped(name1, name2, None),
+        }
+    }
+
+    fn get_roles(&mut self, name: &str, domain: Option<&str>) -> Vec<String> {
+        match domain {
+            Some(domain) => {
+                let mut roles: Vec<String> = self
+                    .resolve_domains(domain)
+                    .into_iter()
+                    .flat_map(|d| self.get_roles_scoped(name, Some(&d)))
+                    .collect();
+                roles.sort_unstable();
+                roles.dedup();
+                roles
+            }
+            None => self.get_roles_scoped(name, None),
+        }
+    }
+
+    fn get_users(&self, name: &str, domain: Option<&str>) -> Vec<String> {
+        match domain {
+            Some(domain) => {
+                let mut users: Vec<String> = self
+                    .resolve_domains(domain)
+                    .into_iter()
+                    .flat_map(|d| self.get_users_scoped(name, Some(&d)))
+                    .collect();
+                users.sort_unstable();
+                users.dedup();
+                users
+            }
+            None => self.get_users_scoped(name, None),
+        }
+    }
 
     fn clear(&mut self) {
         self.all_roles.clear();
+        self.generation += 1;
     }
 }
 
 #[derive(Clone)]
 pub struct Role {
     name: String,
-    roles: Vec<Arc<RwLock<Role>>>,
+    roles: Vec<Edge>,
+    // Memoized transitive closure of `roles`, tagged with the
+    // `DefaultRoleManager` generation it was computed at. A generation
+    // mismatch (or no entry at all) means it must be recomputed.
+    ancestor_cache: Option<(u64, HashSet<String>)>,
 }
 
 impl Role {
@@ -191,60 +538,142 @@ impl Role {
         Role {
             name: name.into(),
             roles: vec![],
+            ancestor_cache: None,
         }
     }
 
     fn add_role(&mut self, other_role: Arc<RwLock<Role>>) {
+        self.add_role_with_params(other_role, vec![]);
+    }
+
+    fn add_role_with_params(&mut self, other_role: Arc<RwLock<Role>>, params: Vec<String>) {
         // drop lock after going out of the scope
         {
             let other_role_locked = other_role.read().unwrap();
             if self
                 .roles
                 .iter()
-                .any(|role| role.read().unwrap().name == other_role_locked.name)
+                .any(|edge| edge.role.read().unwrap().name == other_role_locked.name)
             {
                 return;
             }
         }
-        self.roles.push(other_role);
+        self.roles.push(Edge {
+            role: other_role,
+            params,
+        });
     }
 
     fn delete_role(&mut self, other_role: Arc<RwLock<Role>>) {
         let other_role_locked = other_role.read().unwrap();
         self.roles
-            .retain(|x| x.read().unwrap().name != other_role_locked.name)
+            .retain(|edge| edge.role.read().unwrap().name != other_role_locked.name)
     }
 
-    fn has_role(&self, name: &str, hierarchy_level: usize) -> bool {
+    fn has_role(
+        &mut self,
+        name: &str,
+        hierarchy_level: usize,
+        generation: u64,
+        condition_fn: Option<fn(&[String]) -> bool>,
+    ) -> bool {
         if self.name == name {
             return true;
         }
-        if hierarchy_level == 0 {
-            return false;
-        }
-        for role in self.roles.iter() {
-            if role.read().unwrap().has_role(name, hierarchy_level - 1) {
-                return true;
-            }
-        }
-        false
+        self.ancestors(hierarchy_level, generation, condition_fn)
+            .contains(name)
     }
 
     fn get_roles(&self) -> Vec<String> {
         self.roles
             .iter()
-            .map(|role| role.read().unwrap().name.to_owned())
+            .map(|edge| edge.role.read().unwrap().name.to_owned())
             .collect()
     }
 
+    /// Every ancestor reachable within `hierarchy_level` hops, computed via a
+    /// bounded BFS the first time it's asked for at this `generation` and
+    /// served from cache afterwards. While a `condition_fn` is given, it is
+    /// recomputed on every call instead.
+    fn ancestors(
+        &mut self,
+        hierarchy_level: usize,
+        generation: u64,
+        condition_fn: Option<fn(&[String]) -> bool>,
+    ) -> &HashSet<String> {
+        // A registered condition fn may judge the same params differently
+        // between calls (e.g. a time-bounded grant expiring) without any
+        // add_link/delete_link ever running to bump the generation, so the
+        // cache can't be trusted to reflect it — always recompute instead.
+        let stale = condition_fn.is_some()
+            || !matches!(&self.ancestor_cache, Some((gen, _)) if *gen == generation);
+
+        if stale {
+            self.ancestor_cache = Some((
+                generation,
+                Self::bfs_ancestors(&self.roles, hierarchy_level, condition_fn),
+            ));
+        }
+        &self.ancestor_cache.as_ref().unwrap().1
+    }
+
+    /// Bounded BFS over the role graph, returning every ancestor reachable
+    /// within `hierarchy_level` hops, de-duplicated via a visited set so
+    /// cycles terminate cleanly.
+    fn implicit_roles(
+        &mut self,
+        hierarchy_level: usize,
+        generation: u64,
+        condition_fn: Option<fn(&[String]) -> bool>,
+    ) -> Vec<String> {
+        self.ancestors(hierarchy_level, generation, condition_fn)
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    fn bfs_ancestors(
+        edges: &[Edge],
+        hierarchy_level: usize,
+        condition_fn: Option<fn(&[String]) -> bool>,
+    ) -> HashSet<String> {
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut queue: VecDeque<(Arc<RwLock<Role>>, usize)> = edges
+            .iter()
+            .filter(|edge| edge_active(&edge.params, condition_fn))
+            .map(|edge| (Arc::clone(&edge.role), hierarchy_level))
+            .collect();
+
+        while let Some((role, remaining)) = queue.pop_front() {
+            if remaining == 0 {
+                continue;
+            }
+
+            let role_locked = role.read().unwrap();
+            if !visited.insert(role_locked.name.clone()) {
+                continue;
+            }
+
+            queue.extend(
+                role_locked
+                    .roles
+                    .iter()
+                    .filter(|edge| edge_active(&edge.params, condition_fn))
+                    .map(|edge| (Arc::clone(&edge.role), remaining - 1)),
+            );
+        }
+
+        visited
+    }
+
     fn has_direct_role(&self, name: &str) -> bool {
         self.roles
             .iter()
-            .any(|role| role.read().unwrap().name == name)
+            .any(|edge| edge.role.read().unwrap().name == name)
     }
 
     fn domain(&self) -> Option<&str> {
-        self.name.splitn(2, "::").next()
+        self.name.find("::").map(|idx| &self.name[..idx])
     }
 }
 
@@ -393,6 +822,168 @@ mod tests {
         assert_eq!(false, rm.has_link("u4", "admin", Some("domain2")));
     }
 
+    #[test]
+    fn test_ancestor_cache_invalidated_by_mutation() {
+        let mut rm = DefaultRoleManager::new(3);
+        rm.add_link("u1", "g1", None);
+        rm.add_link("g1", "g2", None);
+
+        // populate the ancestor cache for "u1"
+        assert_eq!(true, rm.has_link("u1", "g2", None));
+
+        // a later link must be visible even though "u1" was already cached
+        rm.add_link("g2", "g3", None);
+        assert_eq!(true, rm.has_link("u1", "g3", None));
+
+        // and removing a link must likewise invalidate the stale cache
+        rm.delete_link("g1", "g2", None).unwrap();
+        assert_eq!(false, rm.has_link("u1", "g2", None));
+        assert_eq!(false, rm.has_link("u1", "g3", None));
+    }
+
+    #[test]
+    fn test_domain_matching_fn() {
+        fn domain_match(pattern: &str, domain: &str) -> bool {
+            if let Some(prefix) = pattern.strip_suffix('*') {
+                domain.starts_with(prefix)
+            } else {
+                pattern == domain
+            }
+        }
+
+        let mut rm = DefaultRoleManager::new(3);
+        rm.add_domain_matching_fn(domain_match);
+        rm.add_link("alice", "admin", Some("tenant_1"));
+        rm.add_link("bob", "admin", Some("tenant_2"));
+
+        assert_eq!(true, rm.has_link("alice", "admin", Some("tenant_*")));
+        assert_eq!(true, rm.has_link("bob", "admin", Some("tenant_*")));
+        assert_eq!(true, rm.has_link("alice", "admin", Some("tenant_1")));
+        assert_eq!(false, rm.has_link("alice", "admin", Some("tenant_2")));
+        assert_eq!(false, rm.has_link("carol", "admin", Some("tenant_*")));
+    }
+
+    #[test]
+    fn test_domain_enumeration() {
+        let mut rm = DefaultRoleManager::new(3);
+        rm.add_link("u1", "g1", Some("domain1"));
+        rm.add_link("u2", "g1", Some("domain2"));
+        rm.add_link("u1", "g2", Some("domain2"));
+        rm.add_link("u5", "g3", None);
+
+        assert_eq!(
+            vec!["domain1", "domain2"],
+            sort_unstable(rm.get_all_domains())
+        );
+        assert_eq!(
+            vec!["domain1", "domain2"],
+            sort_unstable(rm.get_domains_for_user("u1"))
+        );
+        assert_eq!(vec!["domain2"], rm.get_domains_for_user("u2"));
+        assert_eq!(vec![String::new(); 0], rm.get_domains_for_user("u5"));
+        assert_eq!(vec![String::new(); 0], rm.get_domains_for_user("nobody"));
+    }
+
+    #[test]
+    fn test_conditional_link() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Mutex;
+
+        static ACTIVE: Mutex<Option<bool>> = Mutex::new(None);
+        static CALLED: AtomicBool = AtomicBool::new(false);
+
+        fn between(_params: &[String]) -> bool {
+            CALLED.store(true, Ordering::SeqCst);
+            ACTIVE.lock().unwrap().unwrap_or(false)
+        }
+
+        let mut rm = DefaultRoleManager::new(3);
+        *ACTIVE.lock().unwrap() = Some(false);
+        rm.set_link_condition_fn(between);
+
+        rm.add_link("alice", "regular", None);
+        rm.add_link_with_condition(
+            "alice",
+            "temp_admin",
+            None,
+            vec!["2024-01-01".to_owned(), "2024-02-01".to_owned()],
+        );
+
+        // an always-true plain link is unaffected by the predicate
+        assert_eq!(true, rm.has_link("alice", "regular", None));
+        assert_eq!(true, CALLED.load(Ordering::SeqCst));
+
+        // the conditional grant is inactive while the predicate says so
+        assert_eq!(false, rm.has_link("alice", "temp_admin", None));
+
+        // flipping the predicate's answer (without touching the graph)
+        // must be visible on the next query
+        *ACTIVE.lock().unwrap() = Some(true);
+        assert_eq!(true, rm.has_link("alice", "temp_admin", None));
+    }
+
+    #[test]
+    fn test_implicit_roles_and_users() {
+        let mut rm = DefaultRoleManager::new(10);
+        rm.add_link("u1", "g1", None);
+        rm.add_link("u2", "g1", None);
+        rm.add_link("u3", "g2", None);
+        rm.add_link("u4", "g2", None);
+        rm.add_link("u4", "g3", None);
+        rm.add_link("g1", "g3", None);
+
+        assert_eq!(
+            vec!["g1", "g3"],
+            sort_unstable(rm.get_implicit_roles("u1", None))
+        );
+        assert_eq!(
+            vec!["g1", "g3"],
+            sort_unstable(rm.get_implicit_roles("u2", None))
+        );
+        assert_eq!(vec!["g2"], rm.get_implicit_roles("u3", None));
+        assert_eq!(
+            vec!["g2", "g3"],
+            sort_unstable(rm.get_implicit_roles("u4", None))
+        );
+        assert_eq!(vec![String::new(); 0], rm.get_implicit_roles("g3", None));
+
+        // g1 is itself a member of g3, so it is included here alongside the
+        // direct member u4 and the plain users u1 and u2, which only reach
+        // g3 transitively through g1.
+        assert_eq!(
+            vec!["g1", "u1", "u2", "u4"],
+            sort_unstable(rm.get_implicit_users("g3", None))
+        );
+        // u3 is a direct member of g2, so it is included alongside u4.
+        assert_eq!(
+            vec!["u3", "u4"],
+            sort_unstable(rm.get_implicit_users("g2", None))
+        );
+        assert_eq!(vec![String::new(); 0], rm.get_implicit_users("u1", None));
+    }
+
+    #[test]
+    fn test_implicit_roles_respects_hierarchy_level_and_cycles() {
+        let mut rm = DefaultRoleManager::new(1);
+        rm.add_link("u1", "g1", None);
+        rm.add_link("g1", "g2", None);
+        rm.add_link("g2", "g1", None);
+
+        // hierarchy level 1 only reaches the direct parent
+        assert_eq!(vec!["g1"], rm.get_implicit_roles("u1", None));
+
+        let mut rm = DefaultRoleManager::new(10);
+        rm.add_link("u1", "g1", None);
+        rm.add_link("g1", "g2", None);
+        rm.add_link("g2", "g1", None);
+
+        // the g1 <-> g2 cycle must not loop forever or duplicate entries
+        assert_eq!(
+            vec!["g1", "g2"],
+            sort_unstable(rm.get_implicit_roles("u1", None))
+        );
+    }
+
     #[test]
     fn test_users() {
         let mut rm = DefaultRoleManager::new(3);